@@ -0,0 +1,189 @@
+#[cfg(feature = "v4l")]
+mod v4l;
+#[cfg(feature = "v4l")]
+pub use v4l::V4lSource;
+
+use num_traits::FromPrimitive;
+use rxing::{common::HybridBinarizer, BinaryBitmap, Luma8LuminanceSource};
+use std::process::Command;
+
+#[derive(num_derive::FromPrimitive, Debug)]
+pub enum PixelFormat {
+    A8 = 0x00000008,
+    RGBA4444 = 0x00000007,
+    RGBA8888 = 0x00000001,
+    RGB565 = 0x00000004,
+}
+
+impl PixelFormat {
+    fn bytes_per_pixel(&self) -> u32 {
+        match self {
+            PixelFormat::A8 => 1,
+            PixelFormat::RGBA4444 => 2,
+            PixelFormat::RGBA8888 => 4,
+            PixelFormat::RGB565 => 2,
+        }
+    }
+
+    // Converts one pixel's raw bytes into 8-bit luma using Rec. 601 weights
+    // (Y = 0.299R + 0.587G + 0.114B, approximated with 8-bit fixed point).
+    fn get_luma(&self, pixel: &[u8]) -> u8 {
+        let luma601 = |r: u8, g: u8, b: u8| -> u8 {
+            ((77 * r as u32 + 150 * g as u32 + 29 * b as u32) >> 8) as u8
+        };
+
+        match self {
+            PixelFormat::A8 => pixel[0],
+            PixelFormat::RGBA8888 => luma601(pixel[0], pixel[1], pixel[2]),
+            PixelFormat::RGBA4444 => {
+                // Each channel is a 4-bit nibble; expand to 8 bits by repeating it.
+                let (b0, b1) = (pixel[0], pixel[1]);
+                let r = ((b1 >> 4) & 0xF) * 0x11;
+                let g = (b1 & 0xF) * 0x11;
+                let b = ((b0 >> 4) & 0xF) * 0x11;
+                luma601(r, g, b)
+            }
+            PixelFormat::RGB565 => {
+                // Little-endian: b0 = GGGBBBBB, b1 = RRRRRGGG. Expand the 5/6/5 fields to 8 bits.
+                let (b0, b1) = (pixel[0], pixel[1]);
+                let r = (b1 & 0xF8) | (b1 >> 5);
+                let g = ((b1 << 5) & 0xE0) | ((b0 >> 3) & 0x1C) | (b0 >> 6);
+                let b5 = b0 & 0x1F;
+                let b = (b5 << 3) | (b5 >> 2);
+                luma601(r, g, b)
+            }
+        }
+    }
+}
+
+pub struct Image {
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    pixels: Vec<u8>,
+}
+
+impl std::fmt::Debug for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{} (format: {:#?})",
+            self.width, self.height, self.format,
+        )
+    }
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, format: PixelFormat, pixels: Vec<u8>) -> Image {
+        Image {
+            width,
+            height,
+            format,
+            pixels,
+        }
+    }
+
+    pub fn crop_and_create_binary_bitmap(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> BinaryBitmap<HybridBinarizer<Luma8LuminanceSource>> {
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let scale_dim = |dim| (dim * bytes_per_pixel) as usize;
+
+        let scaled_image_width = scale_dim(self.width);
+        let scaled_x = scale_dim(x);
+        let scaled_width = scale_dim(width);
+        let cropped = self
+            .pixels
+            .chunks_exact(scaled_image_width)
+            .skip(y as usize)
+            .take(height as usize)
+            .flat_map(|f| f.iter().skip(scaled_x).take(scaled_width).copied())
+            .collect::<Vec<u8>>();
+        assert_eq!(scale_dim(width * height), cropped.len());
+        let luma_vec: Vec<u8> = cropped
+            .chunks(bytes_per_pixel as usize)
+            .map(|pixel| self.format.get_luma(pixel))
+            .collect();
+        assert_eq!((width * height) as usize, luma_vec.len());
+
+        BinaryBitmap::new(HybridBinarizer::new(Luma8LuminanceSource::new(
+            luma_vec, width, height,
+        )))
+    }
+}
+
+/// A source of frames to decode barcodes from.
+pub trait CaptureSource {
+    fn capture(&self) -> Result<Image, Box<dyn std::error::Error>>;
+}
+
+/// Captures the device screen via the Android `screencap` command.
+pub struct ScreencapSource;
+
+impl CaptureSource for ScreencapSource {
+    fn capture(&self) -> Result<Image, Box<dyn std::error::Error>> {
+        let data = run_screencap()?;
+
+        if data.len() < 12 {
+            // Minimum size for header (3 * 4 bytes)
+            return Err("Invalid screencap data".into());
+        }
+
+        // Parse header information
+        let width = u32::from_le_bytes(data[0..4].try_into()?);
+        let height = u32::from_le_bytes(data[4..8].try_into()?);
+        let pixel_format = u32::from_le_bytes(data[8..12].try_into()?);
+        let pixel_format = PixelFormat::from_u32(pixel_format)
+            .ok_or_else(|| format!("Invalid PixelFormat {pixel_format}"))?;
+
+        // Get pixel data (everything after the 12-byte header)
+        let pixel_data = data[12..].to_vec();
+
+        Ok(Image::new(width, height, pixel_format, pixel_data))
+    }
+}
+
+fn run_screencap() -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let output = Command::new("screencap").output()?;
+
+    if !output.status.success() {
+        return Err("screencap command failed".into());
+    }
+
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a8_luma_is_the_byte_itself() {
+        assert_eq!(PixelFormat::A8.get_luma(&[200]), 200);
+    }
+
+    #[test]
+    fn rgba8888_luma_matches_rec601_weights() {
+        assert_eq!(PixelFormat::RGBA8888.get_luma(&[255, 255, 255, 0]), 255);
+        assert_eq!(PixelFormat::RGBA8888.get_luma(&[0, 0, 0, 255]), 0);
+        assert_eq!(PixelFormat::RGBA8888.get_luma(&[100, 150, 50, 255]), 123);
+    }
+
+    #[test]
+    fn rgba4444_expands_nibbles_before_weighting() {
+        // b1 = 0x5A -> R nibble 5, G nibble A; b0 = 0x30 -> B nibble 3 (low nibble unused).
+        assert_eq!(PixelFormat::RGBA4444.get_luma(&[0x30, 0x5A]), 130);
+    }
+
+    #[test]
+    fn rgb565_expands_5_6_5_fields_before_weighting() {
+        // b1 = 0xF8, b0 = 0x00 -> pure red (R5 = 0b11111, G6 = 0, B5 = 0).
+        assert_eq!(PixelFormat::RGB565.get_luma(&[0x00, 0xF8]), 76);
+        // b1 = 0x00, b0 = 0x1F -> pure blue (R5 = 0, G6 = 0, B5 = 0b11111).
+        assert_eq!(PixelFormat::RGB565.get_luma(&[0x1F, 0x00]), 28);
+    }
+}
@@ -0,0 +1,99 @@
+use super::{CaptureSource, Image, PixelFormat};
+use std::cell::RefCell;
+use v4l::io::traits::CaptureStream;
+use v4l::prelude::*;
+use v4l::video::Capture as _;
+use v4l::FourCC;
+
+/// Captures frames from a v4l2 device, e.g. a desktop webcam.
+///
+/// The device is opened, its format negotiated, and the streaming session started once,
+/// at construction (including a one-time discard of the first, possibly unsettled,
+/// frame) so that repeated `capture()` calls - as happens under `--interval` - just
+/// dequeue the next already-streaming frame instead of paying for a fresh
+/// STREAMON/mmap/STREAMOFF cycle every poll.
+pub struct V4lSource {
+    // Declared before `dev` so it is dropped first: `stream` borrows `dev`'s heap
+    // allocation (see the `new` safety comment below) and must not outlive it.
+    stream: RefCell<MmapStream<'static>>,
+    dev: Box<Device>,
+}
+
+impl V4lSource {
+    pub fn new(device: String) -> Result<Self, Box<dyn std::error::Error>> {
+        let dev = Box::new(Device::with_path(&device)?);
+
+        let mut format = dev.format()?;
+        format.fourcc = FourCC::new(b"MJPG");
+        dev.set_format(&format)?;
+
+        // SAFETY: `dev` is heap-allocated via `Box` and stored alongside `stream` in the
+        // returned `V4lSource`, so its address is stable and it outlives `stream` (the
+        // field order above guarantees `stream` is dropped first).
+        let dev_ref: &'static Device = unsafe { &*(dev.as_ref() as *const Device) };
+        let mut stream = MmapStream::with_buffers(dev_ref, v4l::buffer::Type::VideoCapture, 4)?;
+
+        // The first frame off a freshly opened stream can be black/unsettled while
+        // auto-exposure and white balance converge, so throw it away once, here.
+        let _ = stream.next()?;
+
+        Ok(Self {
+            stream: RefCell::new(stream),
+            dev,
+        })
+    }
+}
+
+impl CaptureSource for V4lSource {
+    fn capture(&self) -> Result<Image, Box<dyn std::error::Error>> {
+        let format = self.dev.format()?;
+        let mut stream = self.stream.borrow_mut();
+        let (frame, _meta) = stream.next()?;
+
+        let rgb = match &format.fourcc.repr {
+            b"MJPG" => decode_mjpeg(frame)?,
+            b"YUYV" => decode_yuyv(frame, format.width, format.height),
+            other => return Err(format!("Unsupported v4l pixel format {other:?}").into()),
+        };
+
+        Ok(Image::new(
+            format.width,
+            format.height,
+            PixelFormat::RGBA8888,
+            rgb_to_rgba(&rgb),
+        ))
+    }
+}
+
+fn decode_mjpeg(frame: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Ok(image::load_from_memory(frame)?.to_rgb8().into_raw())
+}
+
+// Standard BT.601 YUYV422 -> RGB conversion, two pixels per 4-byte macropixel.
+fn decode_yuyv(data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+    for chunk in data.chunks_exact(4) {
+        let (y0, u, y1, v) = (
+            chunk[0] as i32,
+            chunk[1] as i32 - 128,
+            chunk[2] as i32,
+            chunk[3] as i32 - 128,
+        );
+        for y in [y0, y1] {
+            let c = y - 16;
+            let r = (298 * c + 409 * v + 128) >> 8;
+            let g = (298 * c - 100 * u - 208 * v + 128) >> 8;
+            let b = (298 * c + 516 * u + 128) >> 8;
+            rgb.push(r.clamp(0, 255) as u8);
+            rgb.push(g.clamp(0, 255) as u8);
+            rgb.push(b.clamp(0, 255) as u8);
+        }
+    }
+    rgb
+}
+
+fn rgb_to_rgba(rgb: &[u8]) -> Vec<u8> {
+    rgb.chunks_exact(3)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 0xFF])
+        .collect()
+}
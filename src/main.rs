@@ -1,133 +1,40 @@
+mod capture;
+mod stream;
+
+use capture::{CaptureSource, ScreencapSource};
 use clap::Parser;
 use log::{debug, error, info, trace, warn};
-use num_traits::FromPrimitive;
 use rxing::{
-    common::HybridBinarizer, qrcode::QRCodeReader, BinaryBitmap, ImmutableReader,
-    Luma8LuminanceSource,
+    multi::{GenericMultipleBarcodeReader, MultipleBarcodeReader},
+    BarcodeFormat, DecodeHintType, DecodeHintValue, DecodingHintDictionary, MultiFormatReader,
+    Reader,
 };
 use serde_json::json;
-use std::process::Command;
+use std::collections::HashSet;
+use stream::StreamAssembler;
 use tokio::task::JoinHandle;
 
-#[derive(num_derive::FromPrimitive, Debug)]
-enum PixelFormat {
-    A8 = 0x00000008,
-    RGBA4444 = 0x00000007,
-    RGBA8888 = 0x00000001,
-    RGB565 = 0x00000004,
-}
-
-impl PixelFormat {
-    fn bytes_per_pixel(&self) -> u32 {
-        match self {
-            PixelFormat::A8 => 1,
-            PixelFormat::RGBA4444 => 2,
-            PixelFormat::RGBA8888 => 4,
-            PixelFormat::RGB565 => 2,
-        }
-    }
-
-    // Gets some arbitrary channel from the low byte of data
-    fn get_channel(&self, byte: u8) -> u8 {
-        match self {
-            PixelFormat::A8 => byte,
-            PixelFormat::RGBA4444 => byte & 0xF,
-            PixelFormat::RGBA8888 => byte,
-            PixelFormat::RGB565 => byte & 0x1F,
-        }
-    }
-}
-
-struct Image {
-    width: u32,
-    height: u32,
-    format: PixelFormat,
-    pixels: Vec<u8>,
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Source {
+    #[default]
+    Screen,
+    V4l,
 }
 
-impl std::fmt::Debug for Image {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}x{} (format: {:#?})",
-            self.width, self.height, self.format,
-        )
-    }
-}
-
-impl Image {
-    fn new(width: u32, height: u32, format: PixelFormat, pixels: Vec<u8>) -> Image {
-        Image {
-            width,
-            height,
-            format,
-            pixels,
+fn build_capture_source(args: &Args) -> Result<Box<dyn CaptureSource>, Box<dyn std::error::Error>> {
+    match args.source {
+        Source::Screen => Ok(Box::new(ScreencapSource)),
+        Source::V4l => {
+            #[cfg(feature = "v4l")]
+            {
+                Ok(Box::new(capture::V4lSource::new(args.device.clone())?))
+            }
+            #[cfg(not(feature = "v4l"))]
+            {
+                Err("This build was compiled without the 'v4l' feature".into())
+            }
         }
     }
-
-    fn crop_and_create_binary_bitmap(
-        &self,
-        x: u32,
-        y: u32,
-        width: u32,
-        height: u32,
-    ) -> BinaryBitmap<HybridBinarizer<Luma8LuminanceSource>> {
-        let bytes_per_pixel = self.format.bytes_per_pixel();
-        let scale_dim = |dim| (dim * bytes_per_pixel) as usize;
-
-        let scaled_image_width = scale_dim(self.width);
-        let scaled_x = scale_dim(x);
-        let scaled_width = scale_dim(width);
-        let cropped = self
-            .pixels
-            .chunks_exact(scaled_image_width)
-            .skip(y as usize)
-            .take(height as usize)
-            .flat_map(|f| f.iter().skip(scaled_x).take(scaled_width))
-            .collect::<Vec<&u8>>();
-        assert_eq!(scale_dim(width * height), cropped.len());
-        let luma_vec: Vec<u8> = cropped
-            .chunks(bytes_per_pixel as usize)
-            .map(|pixels| self.format.get_channel(*pixels[0]))
-            .collect();
-        assert_eq!((width * height) as usize, luma_vec.len());
-
-        BinaryBitmap::new(HybridBinarizer::new(Luma8LuminanceSource::new(
-            luma_vec, width, height,
-        )))
-    }
-}
-
-fn capture_screen() -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    // Run the screencap command and capture its output
-    let output = Command::new("screencap").output()?;
-
-    if !output.status.success() {
-        return Err("screencap command failed".into());
-    }
-
-    Ok(output.stdout)
-}
-
-fn capture_screen_and_parse() -> Result<Image, Box<dyn std::error::Error>> {
-    let data = capture_screen()?;
-
-    if data.len() < 12 {
-        // Minimum size for header (3 * 4 bytes)
-        return Err("Invalid screencap data".into());
-    }
-
-    // Parse header information
-    let width = u32::from_le_bytes(data[0..4].try_into()?);
-    let height = u32::from_le_bytes(data[4..8].try_into()?);
-    let pixel_format = u32::from_le_bytes(data[8..12].try_into()?);
-    let pixel_format = PixelFormat::from_u32(pixel_format)
-        .ok_or_else(|| format!("Invalid PixelFormat {pixel_format}"))?;
-
-    // Get pixel data (everything after the 12-byte header)
-    let pixel_data = data[12..].to_vec();
-
-    Ok(Image::new(width, height, pixel_format, pixel_data))
 }
 
 #[derive(Parser, Debug)]
@@ -161,6 +68,11 @@ struct Args {
     #[arg(long)]
     interval: Option<humantime::Duration>,
 
+    /// Keep scanning at `--interval` until a new code is found (exit 0) or this
+    /// timeout elapses (exit non-zero), instead of looping forever. Requires `--interval`.
+    #[arg(long, requires = "interval")]
+    timeout: Option<humantime::Duration>,
+
     /// Any strings provided here will be removed from the output text before sending to the KV store.
     #[arg(short, long, num_args=1..)]
     substitute: Vec<String>,
@@ -168,30 +80,142 @@ struct Args {
     /// The key to use for KV API
     #[arg(short, long)]
     key: String,
+
+    /// Decode every barcode (of the `--formats` selected) found in the frame instead of
+    /// stopping at the first match
+    #[arg(long)]
+    multi: bool,
+
+    /// Barcode formats to scan for (qr, aztec, datamatrix, code128, ean13)
+    #[arg(long, num_args = 1.., default_value = "qr")]
+    formats: Vec<String>,
+
+    /// Treat decoded codes as chunks of a multi-frame transfer (`id:index/total:payload`)
+    /// and only send once every chunk has been reassembled
+    #[arg(long)]
+    stream: bool,
+
+    /// How to encode decoded payloads before sending to the KV store. `hex`/`base64`
+    /// send the raw (possibly binary) QR bytes instead of the lossy UTF-8 text. Also
+    /// applies to the reassembled payload when `--stream` is set.
+    #[arg(long, value_enum, default_value_t = Encoding::Text)]
+    encoding: Encoding,
+
+    /// Capture backend to read frames from
+    #[arg(long, value_enum, default_value_t = Source::Screen)]
+    source: Source,
+
+    /// v4l2 device path to capture from when `--source v4l` is used
+    #[arg(long, default_value = "/dev/video0")]
+    device: String,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+enum Encoding {
+    #[default]
+    Text,
+    Hex,
+    Base64,
+}
+
+fn encode_bytes(encoding: Encoding, raw: &[u8]) -> String {
+    match encoding {
+        Encoding::Text => String::from_utf8_lossy(raw).into_owned(),
+        Encoding::Hex => hex::encode(raw),
+        Encoding::Base64 => base64::Engine::encode(&base64::engine::general_purpose::STANDARD, raw),
+    }
+}
+
+/// Encodes a decoded result according to `args.encoding`, returning the payload to send
+/// and, for the raw-byte encodings, extra JSON fields describing the source barcode.
+fn encode_payload(args: &Args, result: &rxing::RXingResult) -> (String, Option<serde_json::Value>) {
+    match args.encoding {
+        Encoding::Text => (result.getText().to_string(), None),
+        Encoding::Hex | Encoding::Base64 => {
+            let raw = result.getRawBytes();
+            let payload = encode_bytes(args.encoding, raw);
+            let extra = json!({
+                "format": format!("{:?}", result.getBarcodeFormat()),
+                "byte_count": raw.len(),
+            });
+            (payload, Some(extra))
+        }
+    }
+}
+
+/// Encodes a fully reassembled `--stream` payload according to `args.encoding`, mirroring
+/// `encode_payload` for the single-chunk case.
+fn encode_reassembled_payload(args: &Args, payload: &str) -> (String, Option<serde_json::Value>) {
+    match args.encoding {
+        Encoding::Text => (payload.to_string(), None),
+        Encoding::Hex | Encoding::Base64 => {
+            let raw = payload.as_bytes();
+            let encoded = encode_bytes(args.encoding, raw);
+            let extra = json!({ "byte_count": raw.len() });
+            (encoded, Some(extra))
+        }
+    }
+}
+
+fn parse_barcode_format(format: &str) -> Result<BarcodeFormat, Box<dyn std::error::Error>> {
+    match format.to_lowercase().as_str() {
+        "qr" | "qrcode" => Ok(BarcodeFormat::QR_CODE),
+        "aztec" => Ok(BarcodeFormat::AZTEC),
+        "datamatrix" => Ok(BarcodeFormat::DATA_MATRIX),
+        "code128" => Ok(BarcodeFormat::CODE_128),
+        "ean13" => Ok(BarcodeFormat::EAN_13),
+        other => Err(format!("Unsupported barcode format '{other}'").into()),
+    }
 }
 
-fn send_to_kv_store(text: &str, args: &Args) -> JoinHandle<()> {
-    let text = text.to_string();
+fn build_decode_hints(args: &Args) -> Result<DecodingHintDictionary, Box<dyn std::error::Error>> {
+    let formats = args
+        .formats
+        .iter()
+        .map(|format| parse_barcode_format(format))
+        .collect::<Result<HashSet<_>, _>>()?;
+
+    let mut hints = DecodingHintDictionary::new();
+    hints.insert(
+        DecodeHintType::POSSIBLE_FORMATS,
+        DecodeHintValue::PossibleFormats(formats),
+    );
+    Ok(hints)
+}
+
+fn send_to_kv_store(
+    payload: &str,
+    extra: Option<serde_json::Value>,
+    args: &Args,
+) -> JoinHandle<()> {
+    let payload = payload.to_string();
     let token = args.token.clone();
     let api_url = args.api_url.clone();
     let key = args.key.clone();
 
-    let text = args
+    let payload = args
         .substitute
         .iter()
-        .fold(text, |text, substitute| text.replace(substitute, ""));
+        .fold(payload, |payload, substitute| payload.replace(substitute, ""));
 
     tokio::spawn(async move {
+        let mut body = serde_json::Map::new();
+        body.insert(key, json!(payload));
+        body.insert("token".to_string(), json!(token));
+        if let Some(extra) = extra.as_ref().and_then(serde_json::Value::as_object) {
+            body.extend(extra.clone());
+        }
+
         let client = reqwest::Client::new();
         let result = client
             .post(api_url)
-            .json(&json!({ key: text, "token": token }))
+            .json(&serde_json::Value::Object(body))
             .send()
             .await;
         result.map_or_else(
             |error| error!("Failed to send to KV Store {error:#?}"),
             |response| {
-                info!("Successfully sent '{text}' to KV Store");
+                info!("Successfully sent '{payload}' to KV Store");
                 trace!("Response: {response:#?}");
             },
         );
@@ -200,7 +224,7 @@ fn send_to_kv_store(text: &str, args: &Args) -> JoinHandle<()> {
 
 enum QrCodeTask {
     NoRequest,
-    Request(JoinHandle<()>),
+    Request(Vec<JoinHandle<()>>),
 }
 
 impl From<()> for QrCodeTask {
@@ -211,26 +235,58 @@ impl From<()> for QrCodeTask {
 
 fn parse_qr_code(
     args: &Args,
-    reader: &QRCodeReader,
-    last_result: &mut String,
+    source: &dyn CaptureSource,
+    reader: &mut MultiFormatReader,
+    hints: &DecodingHintDictionary,
+    seen: &mut HashSet<String>,
+    assembler: &mut StreamAssembler,
 ) -> Result<QrCodeTask, Box<dyn std::error::Error>> {
-    let image = capture_screen_and_parse()?;
+    let image = source.capture()?;
     debug!("Captured image: {image:#?}");
 
     let mut binary_bitmap =
         image.crop_and_create_binary_bitmap(args.x, args.y, args.width, args.height);
-    let result = reader.immutable_decode(&mut binary_bitmap);
-    if let Ok(result) = result {
+
+    let results = if args.multi {
+        // GenericMultipleBarcodeReader::new takes its reader by value, so build a fresh
+        // (cheap, stateless) one here rather than threading the long-lived `reader` through.
+        GenericMultipleBarcodeReader::new(MultiFormatReader::default())
+            .decode_multiple_with_hints(&mut binary_bitmap, hints)
+            .unwrap_or_default()
+    } else {
+        reader
+            .decode_with_hints(&mut binary_bitmap, hints)
+            .into_iter()
+            .collect()
+    };
+
+    let mut tasks = Vec::new();
+    for result in results {
         let text = result.getText();
         debug!("Text: {}", text);
-        if text != last_result {
-            let task = send_to_kv_store(text, &args);
-            info!("Detected new QR code '{text}'");
-            *last_result = text.to_string();
-            return Ok(QrCodeTask::Request(task));
+        if !seen.contains(text) {
+            seen.insert(text.to_string());
+
+            let (payload, extra) = if args.stream {
+                match assembler.ingest(text) {
+                    Some(reassembled) => encode_reassembled_payload(args, &reassembled),
+                    None => continue,
+                }
+            } else {
+                encode_payload(args, &result)
+            };
+
+            let task = send_to_kv_store(&payload, extra, args);
+            info!("Detected new QR code '{payload}'");
+            tasks.push(task);
         }
-    };
-    Ok(QrCodeTask::NoRequest)
+    }
+
+    if tasks.is_empty() {
+        Ok(QrCodeTask::NoRequest)
+    } else {
+        Ok(QrCodeTask::Request(tasks))
+    }
 }
 
 #[tokio::main]
@@ -238,17 +294,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
     let args = Args::parse();
-    let mut last_result = String::new();
+    let mut seen = HashSet::new();
 
-    let reader = QRCodeReader::default();
+    let source = build_capture_source(&args)?;
+    let mut reader = MultiFormatReader::default();
+    let hints = build_decode_hints(&args)?;
+    let mut assembler = StreamAssembler::new();
 
     let interval = args.interval;
+    let deadline = args
+        .timeout
+        .map(|timeout| std::time::SystemTime::now() + timeout.into());
 
     let task = match interval {
         Some(interval) => loop {
             let iteration_start = std::time::Instant::now();
             let next_iteration = iteration_start + interval.into();
-            parse_qr_code(&args, &reader, &mut last_result)?;
+            let task = parse_qr_code(
+                &args,
+                source.as_ref(),
+                &mut reader,
+                &hints,
+                &mut seen,
+                &mut assembler,
+            )?;
+
+            if deadline.is_some() && matches!(task, QrCodeTask::Request(_)) {
+                break task;
+            }
+
+            if let Some(deadline) = deadline {
+                if std::time::SystemTime::now() >= deadline {
+                    error!("Timed out waiting for a QR code");
+                    std::process::exit(1);
+                }
+            }
+
             let iteration_end = std::time::Instant::now();
 
             let parse_duration = iteration_end.duration_since(iteration_start);
@@ -266,14 +347,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     humantime::format_duration(slow_by_duration)
                 );
             } else {
+                // Don't oversleep past the deadline; wake up in time to re-check it promptly.
+                let sleep_duration = match deadline {
+                    Some(deadline) => sleep_duration.min(
+                        deadline
+                            .duration_since(std::time::SystemTime::now())
+                            .unwrap_or(std::time::Duration::ZERO),
+                    ),
+                    None => sleep_duration,
+                };
                 std::thread::sleep(sleep_duration);
             };
         },
-        None => parse_qr_code(&args, &reader, &mut last_result)?,
+        None => parse_qr_code(
+            &args,
+            source.as_ref(),
+            &mut reader,
+            &hints,
+            &mut seen,
+            &mut assembler,
+        )?,
     };
 
     match task {
-        QrCodeTask::Request(task) => task.await?,
+        QrCodeTask::Request(tasks) => {
+            for task in tasks {
+                task.await?;
+            }
+        }
         QrCodeTask::NoRequest => (),
     }
 
@@ -0,0 +1,128 @@
+use log::{debug, info, warn};
+use std::collections::HashMap;
+
+/// Reassembles multi-frame ("animated") QR transfers.
+///
+/// Each decoded QR text is treated as a chunk of the form
+/// `<transfer_id>:<index>/<total>:<payload>`. Chunks are buffered per
+/// `transfer_id` until every slot from `0` to `total - 1` has been seen, at
+/// which point the concatenated payload is returned.
+#[derive(Default)]
+pub struct StreamAssembler {
+    transfers: HashMap<String, Vec<Option<String>>>,
+}
+
+impl StreamAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds one decoded QR text into the assembler. Returns the reassembled
+    /// payload once every chunk for its transfer has arrived, or `None` if
+    /// the transfer is still incomplete or `text` isn't a recognized chunk.
+    pub fn ingest(&mut self, text: &str) -> Option<String> {
+        let (transfer_id, index, total, payload) = Self::parse_chunk(text)?;
+
+        let buffer = self
+            .transfers
+            .entry(transfer_id.to_string())
+            .or_insert_with(|| vec![None; total]);
+        if buffer.len() != total {
+            warn!(
+                "Transfer '{transfer_id}' total changed ({} -> {total}), resetting buffer",
+                buffer.len()
+            );
+            *buffer = vec![None; total];
+        }
+
+        if buffer[index].is_some() {
+            debug!("Ignoring duplicate chunk {index}/{total} for transfer '{transfer_id}'");
+        } else {
+            buffer[index] = Some(payload.to_string());
+        }
+
+        let received = buffer.iter().filter(|chunk| chunk.is_some()).count();
+        info!("Transfer '{transfer_id}': got {received}/{total}");
+
+        if received < total {
+            return None;
+        }
+
+        let payload = buffer
+            .iter()
+            .map(|chunk| chunk.as_deref().unwrap_or(""))
+            .collect::<String>();
+        self.transfers.remove(transfer_id);
+        Some(payload)
+    }
+
+    /// Splits `<transfer_id>:<index>/<total>:<payload>` into its parts.
+    fn parse_chunk(text: &str) -> Option<(&str, usize, usize, &str)> {
+        let (transfer_id, rest) = text.split_once(':')?;
+        let (counts, payload) = rest.split_once(':')?;
+        let (index, total) = counts.split_once('/')?;
+        let index = index.parse().ok()?;
+        let total = total.parse().ok()?;
+        if index >= total {
+            return None;
+        }
+        Some((transfer_id, index, total, payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassembles_in_order_chunks() {
+        let mut assembler = StreamAssembler::new();
+        assert_eq!(assembler.ingest("tx1:0/3:foo"), None);
+        assert_eq!(assembler.ingest("tx1:1/3:bar"), None);
+        assert_eq!(assembler.ingest("tx1:2/3:baz"), Some("foobarbaz".to_string()));
+    }
+
+    #[test]
+    fn reassembles_out_of_order_chunks() {
+        let mut assembler = StreamAssembler::new();
+        assert_eq!(assembler.ingest("tx1:2/3:baz"), None);
+        assert_eq!(assembler.ingest("tx1:0/3:foo"), None);
+        assert_eq!(assembler.ingest("tx1:1/3:bar"), Some("foobarbaz".to_string()));
+    }
+
+    #[test]
+    fn ignores_duplicate_chunk_indices() {
+        let mut assembler = StreamAssembler::new();
+        assert_eq!(assembler.ingest("tx1:0/2:foo"), None);
+        // A repeat of chunk 0 shouldn't overwrite the first payload or complete anything.
+        assert_eq!(assembler.ingest("tx1:0/2:ignored"), None);
+        assert_eq!(assembler.ingest("tx1:1/2:bar"), Some("foobar".to_string()));
+    }
+
+    #[test]
+    fn resets_buffer_when_total_changes() {
+        let mut assembler = StreamAssembler::new();
+        assert_eq!(assembler.ingest("tx1:0/3:foo"), None);
+        // The sender restarted the transfer with a different total; the old chunk 0
+        // shouldn't count towards the new total.
+        assert_eq!(assembler.ingest("tx1:0/2:foo"), None);
+        assert_eq!(assembler.ingest("tx1:1/2:bar"), Some("foobar".to_string()));
+    }
+
+    #[test]
+    fn tracks_independent_transfers_concurrently() {
+        let mut assembler = StreamAssembler::new();
+        assert_eq!(assembler.ingest("tx1:0/2:a"), None);
+        assert_eq!(assembler.ingest("tx2:0/1:b"), Some("b".to_string()));
+        assert_eq!(assembler.ingest("tx1:1/2:c"), Some("ac".to_string()));
+    }
+
+    #[test]
+    fn rejects_chunks_that_are_not_well_formed() {
+        let mut assembler = StreamAssembler::new();
+        assert_eq!(assembler.ingest("not-a-chunk"), None);
+        assert_eq!(assembler.ingest("tx1:nope/3:foo"), None);
+        // Index out of range for its own total.
+        assert_eq!(assembler.ingest("tx1:3/3:foo"), None);
+    }
+}